@@ -1,34 +1,312 @@
 // Test Rust file for repo-map
-use std::collections::HashMap;
+use std::any::{Any, TypeId};
+use std::cell::{Ref, RefCell, RefMut};
+use std::collections::{HashMap, HashSet};
+use std::marker::PhantomData;
+
+pub type EntityId = u32;
+
+/// Marker trait for any value that can be attached to an entity.
+pub trait Component: Any {}
+impl<T: Any> Component for T {}
+
+/// Type-erased, contiguous storage for one component type within an
+/// archetype.
+trait ComponentColumn: Any {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: Component> ComponentColumn for Vec<T> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// One component value on its way into an archetype, still knowing its own
+/// `TypeId` and how to create or extend a column of its own type.
+trait ComponentCell {
+    fn component_type_id(&self) -> TypeId;
+    fn new_column(self: Box<Self>) -> Box<dyn ComponentColumn>;
+    fn push_into(self: Box<Self>, column: &mut dyn ComponentColumn);
+}
+
+impl<T: Component> ComponentCell for T {
+    fn component_type_id(&self) -> TypeId {
+        TypeId::of::<T>()
+    }
+
+    fn new_column(self: Box<Self>) -> Box<dyn ComponentColumn> {
+        Box::new(vec![*self])
+    }
+
+    fn push_into(self: Box<Self>, column: &mut dyn ComponentColumn) {
+        column
+            .as_any_mut()
+            .downcast_mut::<Vec<T>>()
+            .expect("component column type mismatch")
+            .push(*self);
+    }
+}
+
+/// Entities sharing the same set of component types, stored as one `Vec<T>`
+/// column per type.
+#[derive(Default)]
+pub struct Archetype {
+    entities: Vec<EntityId>,
+    columns: HashMap<TypeId, RefCell<Box<dyn ComponentColumn>>>,
+}
+
+impl Archetype {
+    fn signature(&self) -> HashSet<TypeId> {
+        self.columns.keys().copied().collect()
+    }
+
+    fn has(&self, type_id: TypeId) -> bool {
+        self.columns.contains_key(&type_id)
+    }
+
+    fn borrow_column<T: Component>(&self) -> Ref<'_, Vec<T>> {
+        Ref::map(self.columns[&TypeId::of::<T>()].borrow(), |c| {
+            c.as_any()
+                .downcast_ref::<Vec<T>>()
+                .expect("component column type mismatch")
+        })
+    }
+
+    fn borrow_column_mut<T: Component>(&self) -> RefMut<'_, Vec<T>> {
+        RefMut::map(self.columns[&TypeId::of::<T>()].borrow_mut(), |c| {
+            c.as_any_mut()
+                .downcast_mut::<Vec<T>>()
+                .expect("component column type mismatch")
+        })
+    }
+}
+
+/// Archetype-based component storage, grouping entities by the exact set
+/// of component types they carry.
+pub struct World {
+    archetypes: Vec<Archetype>,
+    next_id: EntityId,
+}
+
+impl World {
+    pub fn new() -> Self {
+        Self {
+            archetypes: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Begins spawning a new entity; attach components with `with`.
+    pub fn spawn(&mut self) -> EntityBuilder<'_> {
+        let id = self.next_id;
+        self.next_id += 1;
+        EntityBuilder {
+            world: self,
+            id,
+            parts: Vec::new(),
+        }
+    }
+
+    fn insert(&mut self, id: EntityId, parts: Vec<Box<dyn ComponentCell>>) {
+        let signature: HashSet<TypeId> =
+            parts.iter().map(|part| (**part).component_type_id()).collect();
+        let archetype_index = self
+            .archetypes
+            .iter()
+            .position(|archetype| archetype.signature() == signature)
+            .unwrap_or_else(|| {
+                self.archetypes.push(Archetype::default());
+                self.archetypes.len() - 1
+            });
+
+        let archetype = &mut self.archetypes[archetype_index];
+        for part in parts {
+            let type_id = (*part).component_type_id();
+            match archetype.columns.get(&type_id) {
+                Some(column) => part.push_into(column.borrow_mut().as_mut()),
+                None => {
+                    archetype.columns.insert(type_id, RefCell::new(part.new_column()));
+                }
+            }
+        }
+
+        archetype.entities.push(id);
+    }
+}
+
+/// Accumulates the components of one not-yet-spawned entity.
+pub struct EntityBuilder<'a> {
+    world: &'a mut World,
+    id: EntityId,
+    parts: Vec<Box<dyn ComponentCell>>,
+}
+
+impl<'a> EntityBuilder<'a> {
+    /// Attaches a component. Panics if this entity already has one of type `T`.
+    pub fn with<T: Component>(mut self, component: T) -> Self {
+        let type_id = TypeId::of::<T>();
+        assert!(
+            self.parts.iter().all(|part| (**part).component_type_id() != type_id),
+            "entity already has a component of this type"
+        );
+        self.parts.push(Box::new(component));
+        self
+    }
+
+    pub fn id(self) -> EntityId {
+        let EntityBuilder { world, id, parts } = self;
+        world.insert(id, parts);
+        id
+    }
+}
+
+/// A single `&A`/`&mut A`, or a tuple of those, describing what a `Query` reads.
+pub trait QueryData {
+    type Item<'a>;
+    type Lock<'w>;
+
+    fn type_ids() -> Vec<TypeId>;
+
+    fn matches(archetype: &Archetype) -> bool {
+        Self::type_ids().iter().all(|id| archetype.has(*id))
+    }
+
+    fn lock<'w>(archetype: &'w Archetype) -> Self::Lock<'w>;
+    fn fetch<'a, 'w>(lock: &'a mut Self::Lock<'w>, row: usize) -> Self::Item<'a>;
+}
+
+impl<A: Component> QueryData for &A {
+    type Item<'a> = &'a A;
+    type Lock<'w> = Ref<'w, Vec<A>>;
+
+    fn type_ids() -> Vec<TypeId> {
+        vec![TypeId::of::<A>()]
+    }
+
+    fn lock<'w>(archetype: &'w Archetype) -> Self::Lock<'w> {
+        archetype.borrow_column::<A>()
+    }
+
+    fn fetch<'a, 'w>(lock: &'a mut Self::Lock<'w>, row: usize) -> Self::Item<'a> {
+        &lock[row]
+    }
+}
+
+impl<A: Component> QueryData for &mut A {
+    type Item<'a> = &'a mut A;
+    type Lock<'w> = RefMut<'w, Vec<A>>;
+
+    fn type_ids() -> Vec<TypeId> {
+        vec![TypeId::of::<A>()]
+    }
+
+    fn lock<'w>(archetype: &'w Archetype) -> Self::Lock<'w> {
+        archetype.borrow_column_mut::<A>()
+    }
+
+    fn fetch<'a, 'w>(lock: &'a mut Self::Lock<'w>, row: usize) -> Self::Item<'a> {
+        &mut lock[row]
+    }
+}
+
+macro_rules! impl_query_data_tuple {
+    ($($name:ident),+) => {
+        impl<$($name: QueryData),+> QueryData for ($($name,)+) {
+            type Item<'a> = ($($name::Item<'a>,)+);
+            type Lock<'w> = ($($name::Lock<'w>,)+);
+
+            fn type_ids() -> Vec<TypeId> {
+                let mut ids = Vec::new();
+                $(ids.extend($name::type_ids());)+
+                ids
+            }
+
+            #[allow(non_snake_case)]
+            fn lock<'w>(archetype: &'w Archetype) -> Self::Lock<'w> {
+                ($($name::lock(archetype),)+)
+            }
+
+            #[allow(non_snake_case)]
+            fn fetch<'a, 'w>(lock: &'a mut Self::Lock<'w>, row: usize) -> Self::Item<'a> {
+                let ($($name,)+) = lock;
+                ($($name::fetch($name, row),)+)
+            }
+        }
+    };
+}
+
+impl_query_data_tuple!(A, B);
+impl_query_data_tuple!(A, B, C);
+
+/// Walks only the archetypes matching `Q`, e.g. `Query<(&Transform, &Sprite)>`.
+pub struct Query<'w, Q: QueryData> {
+    world: &'w World,
+    _marker: PhantomData<Q>,
+}
+
+impl<'w, Q: QueryData> Query<'w, Q> {
+    pub fn new(world: &'w World) -> Self {
+        Self { world, _marker: PhantomData }
+    }
+
+    pub fn for_each(&self, mut f: impl FnMut(Q::Item<'_>)) {
+        for archetype in &self.world.archetypes {
+            if !Q::matches(archetype) {
+                continue;
+            }
+            let mut lock = Q::lock(archetype);
+            for row in 0..archetype.entities.len() {
+                f(Q::fetch(&mut lock, row));
+            }
+        }
+    }
+}
 
 pub struct GameEngine {
-    entities: HashMap<u32, Entity>,
+    world: World,
     systems: Vec<Box<dyn System>>,
 }
 
 impl GameEngine {
     pub fn new() -> Self {
         Self {
-            entities: HashMap::new(),
+            world: World::new(),
             systems: Vec::new(),
         }
     }
 
-    pub fn add_entity(&mut self, entity: Entity) -> u32 {
-        let id = self.entities.len() as u32;
-        self.entities.insert(id, entity);
-        id
+    pub fn add_system(&mut self, system: Box<dyn System>) {
+        self.systems.push(system);
+    }
+
+    pub fn spawn(&mut self) -> EntityBuilder<'_> {
+        self.world.spawn()
     }
 
     pub fn update(&mut self, delta_time: f32) {
         for system in &mut self.systems {
-            system.update(delta_time);
+            system.update(&mut self.world, delta_time);
         }
     }
 }
 
 pub trait System {
-    fn update(&mut self, delta_time: f32);
+    fn update(&mut self, world: &mut World, delta_time: f32);
+}
+
+pub struct Transform {
+    pub position: (f32, f32),
+    pub rotation: f32,
+}
+
+pub struct Sprite {
+    pub texture_id: u32,
 }
 
 pub struct RenderSystem {
@@ -36,32 +314,70 @@ pub struct RenderSystem {
 }
 
 impl System for RenderSystem {
-    fn update(&mut self, delta_time: f32) {
-        self.renderer.render();
+    fn update(&mut self, world: &mut World, _delta_time: f32) {
+        Query::<(&Transform, &Sprite)>::new(world).for_each(|(transform, sprite)| {
+            self.renderer.render(transform, sprite);
+        });
     }
 }
 
-pub enum EntityType {
+#[derive(Clone, Copy)]
+pub enum EntityKind {
     Player,
     Enemy,
     Projectile,
 }
 
-pub struct Entity {
-    pub entity_type: EntityType,
-    pub position: (f32, f32),
-    pub velocity: (f32, f32),
-}
+pub struct Position(pub f32, pub f32);
+pub struct Velocity(pub f32, pub f32);
 
-pub fn create_player(x: f32, y: f32) -> Entity {
-    Entity {
-        entity_type: EntityType::Player,
-        position: (x, y),
-        velocity: (0.0, 0.0),
-    }
+pub fn create_player(world: &mut World, x: f32, y: f32) -> EntityId {
+    world
+        .spawn()
+        .with(EntityKind::Player)
+        .with(Position(x, y))
+        .with(Velocity(0.0, 0.0))
+        .id()
 }
 
 pub async fn load_assets() -> Result<AssetManager, String> {
     // Async function example
     Ok(AssetManager::new())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_yields_only_matching_entities_in_spawn_order() {
+        let mut world = World::new();
+        world.spawn().with(Position(1.0, 1.0)).with(Velocity(0.0, 0.0)).id();
+        world.spawn().with(Velocity(5.0, 5.0)).id();
+        world.spawn().with(Position(2.0, 2.0)).id();
+
+        let mut seen = Vec::new();
+        Query::<&Position>::new(&world).for_each(|position| seen.push((position.0, position.1)));
+
+        assert_eq!(seen, vec![(1.0, 1.0), (2.0, 2.0)]);
+    }
+
+    #[test]
+    fn second_entity_in_a_shared_archetype_keeps_its_own_components() {
+        let mut world = World::new();
+        world.spawn().with(Position(1.0, 1.0)).id();
+        world.spawn().with(Position(2.0, 2.0)).id();
+
+        let mut positions = Vec::new();
+        Query::<&Position>::new(&world).for_each(|position| positions.push(position.0));
+
+        assert_eq!(positions, vec![1.0, 2.0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "already has a component")]
+    fn with_rejects_a_duplicate_component_type() {
+        let mut world = World::new();
+        world.spawn().with(Position(0.0, 0.0)).with(Position(1.0, 1.0));
+    }
+}